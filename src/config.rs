@@ -0,0 +1,113 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Environment variable pointing at a file of default arguments to
+/// prepend to argv, e.g. `--hidden` or a default `--threads`.
+const CONFIG_ENV_VAR: &'static str = "XREP_CONFIG_PATH";
+
+/// Build the full argument list for `Docopt` to parse: the config
+/// file's arguments (if any) followed by the process's real
+/// command-line arguments.
+///
+/// Putting the config arguments first and the real argv second means
+/// explicit flags on the command line win: `Docopt` takes the last
+/// occurrence of a repeated single-valued option.
+pub fn resolve_argv() -> Vec<String> {
+    let mut argv: Vec<String> = env::args().collect();
+    let config_args = load();
+    if config_args.is_empty() {
+        return argv;
+    }
+    let rest = argv.split_off(1);
+    argv.extend(config_args);
+    argv.extend(rest);
+    argv
+}
+
+/// Read `$XREP_CONFIG_PATH`, one argument per line. Each line is
+/// trimmed and then taken as-is: shell quoting and escapes are not
+/// interpreted. Blank lines and lines starting with `#` are ignored. A missing env var or
+/// a config file that can't be opened just yields no extra arguments,
+/// since most users won't have one set up.
+fn load() -> Vec<String> {
+    let path = match env::var_os(CONFIG_ENV_VAR) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    let mut args = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        args.push(line.to_string());
+    }
+    for warning in legacy_thread_count_warnings(&args) {
+        eprintln!("{}", warning);
+    }
+    args
+}
+
+/// `-t` used to be the short alias for `--threads`; it's now `--type`.
+/// A config file written against the old CLI and never updated would
+/// have its thread count read as a type name, which then fails as an
+/// "unrecognized file type" error that says nothing about the cause.
+/// We can't tell the two usages apart for certain, but `-t` taking its
+/// value on the following line (as opposed to `-t4`) and that value
+/// being purely numeric is the old usage's signature, so warn about it.
+fn legacy_thread_count_warnings(args: &[String]) -> Vec<String> {
+    let mut warnings = vec![];
+    for window in args.windows(2) {
+        if window[0] == "-t" && window[1].parse::<usize>().is_ok() {
+            warnings.push(format!(
+                "warning: {} looks like the old `-t {}` usage; `-t` is \
+                 now the short form of --type, not --threads. Use \
+                 --threads {} instead.",
+                CONFIG_ENV_VAR, window[1], window[1]));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use docopt::Docopt;
+
+    use {Args, USAGE};
+
+    /// `resolve_argv` relies on `Docopt` taking the last occurrence of
+    /// a repeated single-valued option. Verify that directly: simulate
+    /// a config-file `--threads 2` followed by an explicit `--threads 7`
+    /// and confirm the explicit value wins.
+    #[test]
+    fn explicit_flag_overrides_earlier_config_value() {
+        let argv = vec![
+            "xrep", "--threads", "2", "--threads", "7", "pattern",
+        ];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv.into_iter()).decode())
+            .unwrap();
+        assert_eq!(args.flag_threads, 7);
+    }
+
+    #[test]
+    fn legacy_thread_count_usage_is_detected() {
+        use super::legacy_thread_count_warnings;
+
+        let legacy = vec!["-t".to_string(), "4".to_string()];
+        assert_eq!(legacy_thread_count_warnings(&legacy).len(), 1);
+
+        // The new `-t rust` (type) usage must not be mistaken for it.
+        let current = vec!["-t".to_string(), "rust".to_string()];
+        assert!(legacy_thread_count_warnings(&current).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use glob::Pattern;
+
+/// A single rule parsed out of a `.gitignore` file.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pat: Pattern,
+    whitelist: bool,
+}
+
+impl Rule {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.pat.is_match(text)
+    }
+
+    pub fn is_whitelist(&self) -> bool {
+        self.whitelist
+    }
+}
+
+/// The parsed rules of a single `.gitignore` file.
+#[derive(Clone, Debug, Default)]
+pub struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    /// Parse the `.gitignore` at the given path. Missing files produce
+    /// an empty rule set rather than an error, since most directories
+    /// don't have one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Gitignore {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Gitignore::default(),
+        };
+        let mut rules = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (whitelist, glob) = if line.starts_with('!') {
+                (true, &line[1..])
+            } else {
+                (false, line)
+            };
+            if let Ok(pat) = Pattern::new(glob) {
+                rules.push(Rule { pat: pat, whitelist: whitelist });
+            }
+        }
+        Gitignore { rules: rules }
+    }
+
+    /// Returns whether `text` should be ignored according to these
+    /// rules. Later rules take precedence over earlier ones, matching
+    /// git's own semantics (a later `!pattern` can whitelist a file
+    /// matched by an earlier rule).
+    pub fn matched(&self, text: &str) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.is_match(text) {
+                ignored = !rule.is_whitelist();
+            }
+        }
+        ignored
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
@@ -0,0 +1,96 @@
+/// A tiny glob-to-regex compiler.
+///
+/// This only supports the subset of glob syntax that shows up in
+/// `.gitignore` files and `-t`/`-T` type globs: `*`, `?`, character
+/// classes (`[...]`), and literal text. It does not support `**` or
+/// brace expansion.
+use regex::Regex;
+
+use Result;
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    /// The original glob text, kept around for debugging/printing.
+    original: String,
+    re: Regex,
+}
+
+impl Pattern {
+    /// Compile a glob pattern into something that can be matched
+    /// against a path or file name.
+    pub fn new(glob: &str) -> Result<Pattern> {
+        let re = try!(Regex::new(&glob_to_regex(glob)));
+        Ok(Pattern {
+            original: glob.to_string(),
+            re: re,
+        })
+    }
+
+    /// Returns true if this pattern matches the given text (typically
+    /// a file name or a `/`-separated relative path).
+    pub fn is_match(&self, text: &str) -> bool {
+        self.re.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.original
+    }
+}
+
+/// Translate a glob pattern into an equivalent anchored regex pattern.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::with_capacity(glob.len() + 6);
+    re.push_str("(?:^|/)");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '.' | '(' | ')' | '|' | '+' | '^' | '$' | '\\' => {
+                re.push('\\');
+                re.push(c);
+            }
+            '[' => {
+                re.push('[');
+                if let Some(&next) = chars.peek() {
+                    if next == '!' {
+                        re.push('^');
+                        chars.next();
+                    }
+                }
+                while let Some(&next) = chars.peek() {
+                    chars.next();
+                    if next == ']' {
+                        break;
+                    }
+                    re.push(next);
+                }
+                re.push(']');
+            }
+            _ => re.push(c),
+        }
+    }
+    re.push('$');
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+
+    #[test]
+    fn matches_simple_extension() {
+        let pat = Pattern::new("*.rs").unwrap();
+        assert!(pat.is_match("main.rs"));
+        assert!(pat.is_match("src/main.rs"));
+        assert!(!pat.is_match("main.rs.bak"));
+    }
+
+    #[test]
+    fn matches_exact_name() {
+        let pat = Pattern::new("target").unwrap();
+        assert!(pat.is_match("target"));
+        assert!(pat.is_match("foo/target"));
+        assert!(!pat.is_match("targets"));
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use gitignore::Gitignore;
+
+/// Tracks the ignore rules in effect at a particular point in the
+/// directory tree.
+///
+/// An `Ignore` is cheap to clone: the per-directory `.gitignore` rules
+/// are kept behind `Arc`, so descending into a subdirectory just grows
+/// a small stack rather than copying any parsed rules.
+#[derive(Clone)]
+pub struct Ignore {
+    hidden: bool,
+    stack: Vec<Arc<Gitignore>>,
+}
+
+impl Ignore {
+    /// Create a new `Ignore` with no accumulated `.gitignore` rules.
+    /// Hidden files are ignored by default.
+    pub fn new() -> Ignore {
+        Ignore { hidden: true, stack: vec![] }
+    }
+
+    /// When `yes` is true, dot-files and dot-directories are skipped.
+    pub fn ignore_hidden(&mut self, yes: bool) {
+        self.hidden = yes;
+    }
+
+    /// Return a new `Ignore` scoped to the given directory, with that
+    /// directory's `.gitignore` (if any) layered on top of the current
+    /// rule stack.
+    pub fn push(&self, dir: &Path) -> Ignore {
+        let gi = Gitignore::open(dir.join(".gitignore"));
+        if gi.is_empty() {
+            return self.clone();
+        }
+        let mut stack = self.stack.clone();
+        stack.push(Arc::new(gi));
+        Ignore { hidden: self.hidden, stack: stack }
+    }
+
+    /// Returns true if `path` should be skipped entirely: either it's
+    /// a dot-file/dot-directory and hidden files are disabled, or some
+    /// `.gitignore` rule on the stack matches it.
+    pub fn ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.hidden {
+            let is_hidden = path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with('.'))
+                .unwrap_or(false);
+            if is_hidden {
+                return true;
+            }
+        }
+        let text = path.to_string_lossy();
+        let mut ignored = false;
+        for gi in &self.stack {
+            if gi.matched(&text) {
+                ignored = true;
+            }
+        }
+        let _ = is_dir;
+        ignored
+    }
+}
@@ -4,6 +4,7 @@ extern crate crossbeam;
 extern crate docopt;
 extern crate env_logger;
 extern crate grep;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate memchr;
@@ -18,12 +19,13 @@ extern crate walkdir;
 
 use std::cmp;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::result;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 
 use crossbeam::sync::SegQueue;
@@ -34,7 +36,7 @@ use walkdir::WalkDir;
 
 use ignore::Ignore;
 use printer::Printer;
-use search::{InputBuffer, Searcher};
+use search::{self, InputBuffer, Searcher};
 
 macro_rules! errored {
     ($($tt:tt)*) => {
@@ -49,22 +51,31 @@ macro_rules! eprintln {
     }}
 }
 
+mod config;
 mod gitignore;
 mod glob;
 mod ignore;
 mod printer;
 mod search;
+mod types;
 mod walk;
 
 const USAGE: &'static str = "
 Usage: xrep [options] <pattern> [<path> ...]
-       xrep --files [<path> ...]
+       xrep --files [options] [<path> ...]
+       xrep --type-list [options]
 
 xrep is like the silver searcher and grep, but faster than both.
 
-WARNING: Searching stdin isn't yet supported.
+When <path> is omitted and stdin isn't connected to a terminal, or when
+<path> is given as `-`, xrep searches stdin instead of walking a directory.
 
 Options:
+    -A, --after-context NUM    Show NUM lines after each match. [default: 0]
+    -B, --before-context NUM   Show NUM lines before each match. [default: 0]
+    -C, --context NUM          Show NUM lines before and after each match.
+                                Overrides --after-context/--before-context.
+                                [default: 0]
     -c, --count         Suppress normal output and show count of line matches.
     --debug             Show debug messages.
     --files             Print each file that would be searched
@@ -72,28 +83,69 @@ Options:
     -L, --follow        Follow symlinks.
     --hidden            Search hidden directories and files.
     -i, --ignore-case   Case insensitive search.
-    -t, --threads ARG   The number of threads to use. Defaults to the number
+    --print-args        Print the fully-resolved argument list (including
+                        anything contributed by $XREP_CONFIG_PATH) and exit.
+    --threads ARG       The number of threads to use. Defaults to the number
                         of logical CPUs. [default: 0]
+    -t, --type NAME     Only search files matching the named type. May be
+                        repeated; a file is searched if it matches any of
+                        them. NOTE: the old -t <num> thread-count shorthand
+                        now means --type; switch scripts or config files
+                        to --threads instead.
+    -T, --type-not NAME  Do not search files matching the named type. May be
+                        repeated.
+    --type-list         List all known file types and their globs, then exit.
+                        Combine with --type-add to see custom types
+                        included in the listing, e.g.:
+                            xrep --type-list --type-add 'foo:*.foo'
+    --type-add ARG      Add a file type definition, given as 'name:glob'.
+                        May be repeated. Merges with the built-in types
+                        rather than replacing them.
+
+ENVIRONMENT:
+    XREP_CONFIG_PATH    Path to a file of default arguments, one per line.
+                        Blank lines and lines starting with # are ignored.
+                        Arguments given explicitly on the command line
+                        override those from the config file. A legacy -t
+                        line followed by a NUM line (the old --threads
+                        shorthand) makes xrep print a warning to stderr,
+                        since -t is now short for --type.
 ";
 
 #[derive(RustcDecodable)]
 struct Args {
     arg_pattern: String,
     arg_path: Vec<String>,
+    flag_after_context: usize,
+    flag_before_context: usize,
+    flag_context: usize,
     flag_count: bool,
     flag_debug: bool,
     flag_files: bool,
     flag_follow: bool,
     flag_hidden: bool,
     flag_ignore_case: bool,
+    flag_print_args: bool,
     flag_threads: usize,
+    flag_type: Vec<String>,
+    flag_type_not: Vec<String>,
+    flag_type_list: bool,
+    flag_type_add: Vec<String>,
 }
 
 pub type Result<T> = result::Result<T, Box<Error + Send + Sync>>;
 
 fn main() {
-    let args: Args = Docopt::new(USAGE).and_then(|d| d.decode())
-                                       .unwrap_or_else(|e| e.exit());
+    let argv = config::resolve_argv();
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(argv.iter()).decode())
+        .unwrap_or_else(|e| e.exit());
+    if args.flag_print_args {
+        for arg in argv.iter().skip(1) {
+            println!("{}", arg);
+        }
+        return;
+    }
     match run(args) {
         Ok(_) => process::exit(0),
         Err(err) => {
@@ -114,11 +166,16 @@ fn run(mut args: Args) -> Result<()> {
         errored!("failed to initialize logger: {}", err);
     }
 
-    if args.arg_path.is_empty() {
-        args.arg_path.push("./".to_string());
+    if args.flag_type_list {
+        let types = try!(types::Types::new(&args.flag_type_add));
+        types.print_list();
+        return Ok(());
     }
-    if args.arg_path.iter().any(|p| p == "-") {
-        errored!("searching <stdin> isn't yet supported");
+
+    if args.arg_path.is_empty() && !stdin_is_tty() {
+        args.arg_path.push("-".to_string());
+    } else if args.arg_path.is_empty() {
+        args.arg_path.push("./".to_string());
     }
     if args.flag_files {
         return run_files(args);
@@ -126,33 +183,45 @@ fn run(mut args: Args) -> Result<()> {
     let args = Arc::new(args);
     let mut workers = vec![];
     let stdout = Arc::new(Mutex::new(io::BufWriter::new(io::stdout())));
+    let num_workers = args.num_workers();
+    let type_filter = Arc::new(try!(args.type_filter()));
 
-    let chan_work_send = {
-        let chan_work = Arc::new(SegQueue::new());
-        for _ in 0..args.num_workers() {
-            let grepb =
-                GrepBuilder::new(&args.arg_pattern)
-                .case_insensitive(args.flag_ignore_case);
-            let worker = Worker {
-                args: args.clone(),
-                stdout: stdout.clone(),
-                chan_work: chan_work.clone(),
-                inpbuf: InputBuffer::new(),
-                outbuf: Some(vec![]),
-                grep: try!(grepb.build()),
-            };
-            workers.push(thread::spawn(move || worker.run()));
-        }
-        chan_work
-    };
-
+    // Rather than walking the whole tree on this thread and handing
+    // workers a fully-enumerated file list, we seed the queue with
+    // just the root paths and let the workers expand directories
+    // themselves, stealing subdirectories from one another as they go.
+    let chan_work = Arc::new(SegQueue::new());
+    let active = Arc::new(AtomicUsize::new(0));
     for p in &args.arg_path {
-        for path in args.walker(p) {
-            chan_work_send.push(Message::Some(path));
+        if p == "-" {
+            chan_work.push(Message::Some(Entry::Subject(Subject::Stdin)));
+        } else if Path::new(p).is_dir() {
+            chan_work.push(
+                Message::Some(Entry::Dir(PathBuf::from(p), args.base_ignore())));
+        } else {
+            // An explicit file argument is always searched, even if it
+            // would otherwise be excluded by the ignore rules.
+            chan_work.push(
+                Message::Some(Entry::Subject(Subject::File(PathBuf::from(p)))));
         }
     }
-    for _ in 0..workers.len() {
-        chan_work_send.push(Message::Quit);
+
+    for _ in 0..num_workers {
+        let grepb =
+            GrepBuilder::new(&args.arg_pattern)
+            .case_insensitive(args.flag_ignore_case);
+        let worker = Worker {
+            args: args.clone(),
+            stdout: stdout.clone(),
+            chan_work: chan_work.clone(),
+            active: active.clone(),
+            num_workers: num_workers,
+            type_filter: type_filter.clone(),
+            inpbuf: InputBuffer::new(),
+            outbuf: Some(vec![]),
+            grep: try!(grepb.build()),
+        };
+        workers.push(thread::spawn(move || worker.run()));
     }
     for worker in workers {
         worker.join().unwrap();
@@ -162,8 +231,12 @@ fn run(mut args: Args) -> Result<()> {
 
 fn run_files(args: Args) -> Result<()> {
     let mut printer = Printer::new(io::BufWriter::new(io::stdout()));
+    let type_filter = try!(args.type_filter());
     for p in &args.arg_path {
         for path in args.walker(p) {
+            if type_filter.excluded(&path.to_string_lossy()) {
+                continue;
+            }
             printer.path(path);
         }
     }
@@ -185,9 +258,31 @@ impl Args {
 
     fn walker<P: AsRef<Path>>(&self, path: P) -> walk::Iter {
         let wd = WalkDir::new(path).follow_links(self.flag_follow);
+        walk::Iter::new(self.base_ignore(), wd)
+    }
+
+    fn base_ignore(&self) -> Ignore {
         let mut ig = Ignore::new();
         ig.ignore_hidden(!self.flag_hidden);
-        walk::Iter::new(ig, wd)
+        ig
+    }
+
+    /// Resolve the effective (before, after) context line counts.
+    /// `--context` is shorthand for setting both at once and takes
+    /// precedence over the individual flags.
+    fn context(&self) -> (usize, usize) {
+        if self.flag_context > 0 {
+            (self.flag_context, self.flag_context)
+        } else {
+            (self.flag_before_context, self.flag_after_context)
+        }
+    }
+
+    /// Build the `-t`/`-T` filter from the registry of built-in and
+    /// `--type-add` file types.
+    fn type_filter(&self) -> Result<types::TypeFilter> {
+        let types = try!(types::Types::new(&self.flag_type_add));
+        types.filter(&self.flag_type, &self.flag_type_not)
     }
 }
 
@@ -196,10 +291,47 @@ enum Message<T> {
     Quit,
 }
 
+/// A single haystack `Worker` knows how to search, independent of where
+/// its bytes actually come from.
+enum Subject {
+    /// The process's standard input.
+    Stdin,
+    /// A regular file, either named explicitly on the command line or
+    /// discovered while walking a directory.
+    File(PathBuf),
+}
+
+impl Subject {
+    fn path(&self) -> &Path {
+        match *self {
+            Subject::Stdin => Path::new("<stdin>"),
+            Subject::File(ref path) => path,
+        }
+    }
+}
+
+/// A unit of work pulled off the shared queue: either a directory that
+/// still needs to be expanded, or a subject that's ready to search.
+enum Entry {
+    Dir(PathBuf, Ignore),
+    Subject(Subject),
+}
+
 struct Worker {
     args: Arc<Args>,
     stdout: Arc<Mutex<io::BufWriter<io::Stdout>>>,
-    chan_work: Arc<SegQueue<Message<PathBuf>>>,
+    chan_work: Arc<SegQueue<Message<Entry>>>,
+    // The number of workers currently holding an entry popped off
+    // `chan_work` but not yet fully processed. A worker must bump this
+    // *before* attempting to pop, and only bring it back down once it
+    // has finished expanding a directory (including pushing all of its
+    // children back onto the queue) or finished searching a file.
+    // Otherwise a worker could see an empty queue while a sibling is
+    // mid-expansion and quit before that sibling's children ever show
+    // up.
+    active: Arc<AtomicUsize>,
+    num_workers: usize,
+    type_filter: Arc<types::TypeFilter>,
     inpbuf: InputBuffer,
     outbuf: Option<Vec<u8>>,
     grep: Grep,
@@ -208,40 +340,201 @@ struct Worker {
 impl Worker {
     fn run(mut self) {
         loop {
-            let path = match self.chan_work.try_pop() {
-                None => continue,
-                Some(Message::Quit) => break,
-                Some(Message::Some(path)) => path,
-            };
-            let file = match File::open(&path) {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("{}: {}", path.display(), err);
+            self.active.fetch_add(1, Ordering::SeqCst);
+            let entry = match self.chan_work.try_pop() {
+                Some(Message::Some(entry)) => entry,
+                Some(Message::Quit) => {
+                    self.active.fetch_sub(1, Ordering::SeqCst);
+                    break;
+                }
+                None => {
+                    self.active.fetch_sub(1, Ordering::SeqCst);
+                    // A push only ever happens while its worker is
+                    // still counted active, so once active hits 0 no
+                    // one can be mid-expansion and nothing new can be
+                    // pushed from here on. But a sibling may have
+                    // pushed an entry and then dropped back to 0 just
+                    // before this load, leaving it sitting unclaimed
+                    // in the queue. Re-validate with one more pop
+                    // before committing to shut the pool down; if it
+                    // turns up real work, push it back and let the
+                    // normal top-of-loop logic pick it up instead.
+                    if self.active.load(Ordering::SeqCst) == 0 {
+                        match self.chan_work.try_pop() {
+                            Some(entry) => self.chan_work.push(entry),
+                            None => {
+                                for _ in 0..self.num_workers {
+                                    self.chan_work.push(Message::Quit);
+                                }
+                            }
+                        }
+                    }
                     continue;
                 }
             };
-            let mut outbuf = self.outbuf.take().unwrap();
-            outbuf.clear();
-            let mut printer = self.args.printer(outbuf);
-            {
-                let searcher = Searcher {
-                    grep: &self.grep,
-                    path: &path,
-                    haystack: file,
-                    inp: &mut self.inpbuf,
-                    printer: &mut printer,
-                };
-                if let Err(err) = searcher.run() {
-                    eprintln!("{}", err);
+            match entry {
+                Entry::Dir(dir, ig) => self.visit_dir(&dir, &ig),
+                Entry::Subject(subject) => self.search(&subject),
+            }
+            self.active.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Read the entries of `dir`, filtering them through `ig`. Files
+    /// that survive the filter are searched right away; directories
+    /// are pushed back onto the shared queue so any idle worker can
+    /// pick them up.
+    fn visit_dir(&mut self, dir: &Path, ig: &Ignore) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("{}: {}", dir.display(), err);
+                return;
+            }
+        };
+        let dir_ig = ig.push(dir);
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            let is_dir = match entry.file_type() {
+                Ok(ft) => entry_is_dir(&path, ft, self.args.flag_follow),
+                Err(_) => continue,
+            };
+            if dir_ig.ignored(&path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                self.chan_work.push(Message::Some(Entry::Dir(path, dir_ig.clone())));
+            } else {
+                // Consult the -t/-T filter here, before the file ever
+                // reaches a search, so excluded files are never opened.
+                if self.type_filter.excluded(&path.to_string_lossy()) {
+                    continue;
                 }
+                self.search(&Subject::File(path));
+            }
+        }
+    }
+
+    fn search(&mut self, subject: &Subject) {
+        match *subject {
+            Subject::Stdin => self.search_stream(subject.path(), io::stdin()),
+            Subject::File(ref path) => {
+                match File::open(path) {
+                    Ok(file) => self.search_file(path, file),
+                    Err(err) => eprintln!("{}: {}", path.display(), err),
+                }
+            }
+        }
+    }
+
+    /// Search a non-seekable stream, such as stdin, using the
+    /// incremental line-buffered reader. There's nothing to `mmap`
+    /// here.
+    fn search_stream<R: io::Read>(&mut self, path: &Path, haystack: R) {
+        let mut outbuf = self.outbuf.take().unwrap();
+        outbuf.clear();
+        let mut printer = self.args.printer(outbuf);
+        {
+            let (before_context, after_context) = self.args.context();
+            let searcher = Searcher {
+                grep: &self.grep,
+                path: path,
+                haystack: haystack,
+                inp: &mut self.inpbuf,
+                printer: &mut printer,
+                before_context: before_context,
+                after_context: after_context,
+            };
+            if let Err(err) = searcher.run() {
+                eprintln!("{}", err);
             }
-            let outbuf = printer.into_inner();
-            if !outbuf.is_empty() {
-                let mut stdout = self.stdout.lock();
-                let _ = stdout.write_all(&outbuf);
-                let _ = stdout.flush();
+        }
+        self.flush(printer);
+    }
+
+    /// Search a file on disk. Large regular files are searched via
+    /// `mmap` to avoid copying them into `InputBuffer`; everything else
+    /// falls back to the incremental reader.
+    fn search_file(&mut self, path: &Path, file: File) {
+        let mut outbuf = self.outbuf.take().unwrap();
+        outbuf.clear();
+        let mut printer = self.args.printer(outbuf);
+        {
+            let (before_context, after_context) = self.args.context();
+            let result = search::search_file(
+                &self.grep, path, file, &mut self.inpbuf, &mut printer,
+                before_context, after_context);
+            if let Err(err) = result {
+                eprintln!("{}", err);
             }
-            self.outbuf = Some(outbuf);
         }
+        self.flush(printer);
+    }
+
+    fn flush(&mut self, printer: Printer<Vec<u8>>) {
+        let outbuf = printer.into_inner();
+        if !outbuf.is_empty() {
+            let mut stdout = self.stdout.lock();
+            let _ = stdout.write_all(&outbuf);
+            let _ = stdout.flush();
+        }
+        self.outbuf = Some(outbuf);
+    }
+}
+
+/// Decide whether `path` should be treated as a directory while
+/// walking. `fs::DirEntry::file_type()` is symlink-metadata-based, so a
+/// symlinked directory always reports `is_dir() == false`; when
+/// `follow` is set (`-L`/`--follow`) we resolve the symlink via
+/// `fs::metadata` to see what it actually points at.
+fn entry_is_dir(path: &Path, file_type: fs::FileType, follow: bool) -> bool {
+    if file_type.is_dir() {
+        return true;
+    }
+    if follow && file_type.is_symlink() {
+        return fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+    }
+    false
+}
+
+#[cfg(unix)]
+fn stdin_is_tty() -> bool {
+    unsafe { libc::isatty(0) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stdin_is_tty() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::entry_is_dir;
+
+    #[test]
+    #[cfg(unix)]
+    fn follows_symlinked_directories_only_when_asked() {
+        use std::os::unix::fs::symlink;
+
+        let base = env::temp_dir()
+            .join(format!("xrep-test-follow-{}", process::id()));
+        let real_dir = base.join("real");
+        let link = base.join("link");
+        fs::create_dir_all(&real_dir).unwrap();
+        symlink(&real_dir, &link).unwrap();
+
+        let file_type = fs::symlink_metadata(&link).unwrap().file_type();
+        assert!(!entry_is_dir(&link, file_type, false));
+        assert!(entry_is_dir(&link, file_type, true));
+
+        fs::remove_dir_all(&base).unwrap();
     }
 }
@@ -0,0 +1,72 @@
+use std::io;
+use std::path::Path;
+
+/// Formats search results and writes them to an underlying writer.
+///
+/// A `Printer` doesn't know anything about matching; it just knows how
+/// to render what `Searcher` hands it.
+pub struct Printer<W> {
+    wtr: W,
+}
+
+impl<W: io::Write> Printer<W> {
+    pub fn new(wtr: W) -> Printer<W> {
+        Printer { wtr: wtr }
+    }
+
+    /// Print a bare path, one per line. Used by `--files`.
+    pub fn path<P: AsRef<Path>>(&mut self, path: P) {
+        let _ = writeln!(self.wtr, "{}", path.as_ref().display());
+    }
+
+    /// Print a single matching line.
+    pub fn matched<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        line_number: Option<u64>,
+        line: &[u8],
+    ) {
+        let _ = write!(self.wtr, "{}:", path.as_ref().display());
+        if let Some(n) = line_number {
+            let _ = write!(self.wtr, "{}:", n);
+        }
+        let _ = self.wtr.write_all(line);
+        if line.last() != Some(&b'\n') {
+            let _ = self.wtr.write_all(b"\n");
+        }
+    }
+
+    /// Print the total number of matching lines found in `path`.
+    pub fn count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        let _ = writeln!(self.wtr, "{}:{}", path.as_ref().display(), count);
+    }
+
+    /// Print a context line (from `-A`/`-B`/`-C`). Uses a `-` separator
+    /// instead of `:` so context lines are visually distinct from
+    /// matching lines.
+    pub fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        line_number: Option<u64>,
+        line: &[u8],
+    ) {
+        let _ = write!(self.wtr, "{}-", path.as_ref().display());
+        if let Some(n) = line_number {
+            let _ = write!(self.wtr, "{}-", n);
+        }
+        let _ = self.wtr.write_all(line);
+        if line.last() != Some(&b'\n') {
+            let _ = self.wtr.write_all(b"\n");
+        }
+    }
+
+    /// Print the `--` separator between two non-adjacent groups of
+    /// match/context lines.
+    pub fn separator(&mut self) {
+        let _ = self.wtr.write_all(b"--\n");
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
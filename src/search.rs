@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use memchr::memchr;
+use memmap::{Mmap, Protection};
+use grep::Grep;
+
+use printer::Printer;
+use Result;
+
+const INITIAL_BUFFER_SIZE: usize = 8 * (1 << 10); // 8 KiB
+
+/// Files at or above this size are searched via `mmap` instead of
+/// through the incremental reader, to avoid copying their contents
+/// into `InputBuffer`.
+const MMAP_THRESHOLD: u64 = 64 * (1 << 10); // 64 KiB
+
+/// A growable buffer used to incrementally read a haystack and split
+/// it into lines, so `Searcher` never needs the whole file in memory
+/// up front.
+pub struct InputBuffer {
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+}
+
+impl InputBuffer {
+    pub fn new() -> InputBuffer {
+        InputBuffer { buf: vec![0; INITIAL_BUFFER_SIZE], pos: 0, end: 0 }
+    }
+
+    /// Reset to an empty buffer so it can be reused for the next file.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.end = 0;
+    }
+
+    /// Refill the buffer from `rdr`, compacting any unread bytes to the
+    /// front and growing the buffer if it's already full. Returns the
+    /// number of bytes read; `0` signals EOF.
+    fn fill<R: Read>(&mut self, rdr: &mut R) -> io::Result<usize> {
+        if self.pos > 0 {
+            let len = self.buf.len();
+            self.buf.drain(..self.pos);
+            self.buf.resize(len, 0);
+            self.end -= self.pos;
+            self.pos = 0;
+        }
+        if self.end == self.buf.len() {
+            let new_len = self.buf.len() * 2;
+            self.buf.resize(new_len, 0);
+        }
+        let n = try!(rdr.read(&mut self.buf[self.end..]));
+        self.end += n;
+        Ok(n)
+    }
+}
+
+/// Searches a single streamed haystack (a file or stdin) for matches of
+/// `grep` and reports them through `printer`. Memory-mapped files are
+/// searched by `search_bytes` instead.
+pub struct Searcher<'a, R, W: 'a> {
+    pub grep: &'a Grep,
+    pub path: &'a Path,
+    pub haystack: R,
+    pub inp: &'a mut InputBuffer,
+    pub printer: &'a mut Printer<W>,
+    /// Number of lines of context to print before a match.
+    pub before_context: usize,
+    /// Number of lines of context to print after a match.
+    pub after_context: usize,
+}
+
+impl<'a, R: Read, W: io::Write> Searcher<'a, R, W> {
+    /// Search the haystack line-by-line, reading incrementally through
+    /// `inp`. Returns the number of matching lines found.
+    pub fn run(self) -> Result<u64> {
+        let Searcher {
+            grep, path, mut haystack, inp, printer,
+            before_context, after_context,
+        } = self;
+        inp.reset();
+
+        let mut state = SearchState {
+            path: path,
+            printer: printer,
+            count: 0,
+            after_remaining: 0,
+            after_context: after_context,
+            last_printed: 0,
+            before_context: before_context,
+            before_buf: VecDeque::with_capacity(before_context),
+        };
+
+        let mut line_number = 0u64;
+        loop {
+            let nread = try!(inp.fill(&mut haystack));
+            loop {
+                let idx = memchr(b'\n', &inp.buf[inp.pos..inp.end]);
+                let end = match idx {
+                    Some(idx) => inp.pos + idx + 1,
+                    None => break,
+                };
+                line_number += 1;
+                state.handle_line(grep, line_number, &inp.buf[inp.pos..end]);
+                inp.pos = end;
+            }
+            if nread == 0 {
+                if inp.pos < inp.end {
+                    line_number += 1;
+                    let line = &inp.buf[inp.pos..inp.end];
+                    state.handle_line(grep, line_number, line);
+                    inp.pos = inp.end;
+                }
+                break;
+            }
+        }
+        Ok(state.count)
+    }
+}
+
+/// Search a file on disk, preferring `mmap` over the incremental
+/// reader when the file is a large regular file.
+///
+/// The mapping's lifetime is scoped to this call, so the `&[u8]` it
+/// hands to `search_bytes` never outlives it. Any file that isn't a
+/// plain, non-empty, large-enough regular file — or that simply fails
+/// to map, e.g. a special file on Linux that reports a size but can't
+/// actually be mapped — falls back to the incremental reader instead.
+pub fn search_file<W: io::Write>(
+    grep: &Grep,
+    path: &Path,
+    file: File,
+    inp: &mut InputBuffer,
+    printer: &mut Printer<W>,
+    before_context: usize,
+    after_context: usize,
+) -> Result<u64> {
+    if let Some(mmap) = try_mmap(&file) {
+        let bytes = unsafe { mmap.as_slice() };
+        return Ok(search_bytes(
+            grep, path, bytes, printer, before_context, after_context));
+    }
+    let searcher = Searcher {
+        grep: grep,
+        path: path,
+        haystack: file,
+        inp: inp,
+        printer: printer,
+        before_context: before_context,
+        after_context: after_context,
+    };
+    searcher.run()
+}
+
+fn try_mmap(file: &File) -> Option<Mmap> {
+    let meta = match file.metadata() {
+        Ok(meta) => meta,
+        Err(_) => return None,
+    };
+    if !meta.is_file() || meta.len() == 0 || meta.len() < MMAP_THRESHOLD {
+        return None;
+    }
+    Mmap::open(file, Protection::Read).ok()
+}
+
+/// Search an in-memory byte slice directly, without going through
+/// `InputBuffer` at all.
+fn search_bytes<W: io::Write>(
+    grep: &Grep,
+    path: &Path,
+    bytes: &[u8],
+    printer: &mut Printer<W>,
+    before_context: usize,
+    after_context: usize,
+) -> u64 {
+    let mut state = SearchState {
+        path: path,
+        printer: printer,
+        count: 0,
+        after_remaining: 0,
+        after_context: after_context,
+        last_printed: 0,
+        before_context: before_context,
+        before_buf: VecDeque::with_capacity(before_context),
+    };
+    let mut pos = 0;
+    let mut line_number = 0u64;
+    while pos < bytes.len() {
+        let end = match memchr(b'\n', &bytes[pos..]) {
+            Some(idx) => pos + idx + 1,
+            None => bytes.len(),
+        };
+        line_number += 1;
+        state.handle_line(grep, line_number, &bytes[pos..end]);
+        pos = end;
+    }
+    state.count
+}
+
+/// The bits of search state that need to survive across lines: the
+/// running match count, the after-context countdown, and a ring buffer
+/// of recent non-matching lines kept around in case the next line
+/// turns out to be a match.
+struct SearchState<'a, W: 'a> {
+    path: &'a Path,
+    printer: &'a mut Printer<W>,
+    count: u64,
+    after_remaining: usize,
+    after_context: usize,
+    before_context: usize,
+    before_buf: VecDeque<(u64, Vec<u8>)>,
+    last_printed: u64,
+}
+
+impl<'a, W: io::Write> SearchState<'a, W> {
+    fn handle_line(&mut self, grep: &Grep, line_number: u64, line: &[u8]) {
+        if grep.is_match(line) {
+            self.count += 1;
+            while let Some((n, l)) = self.before_buf.pop_front() {
+                self.emit_context(n, &l);
+            }
+            self.emit_match(line_number, line);
+            self.after_remaining = self.after_context;
+        } else if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            self.emit_context(line_number, line);
+        } else if self.before_context > 0 {
+            if self.before_buf.len() == self.before_context {
+                self.before_buf.pop_front();
+            }
+            self.before_buf.push_back((line_number, line.to_vec()));
+        }
+    }
+
+    fn emit_match(&mut self, line_number: u64, line: &[u8]) {
+        self.maybe_separator(line_number);
+        self.printer.matched(self.path, Some(line_number), line);
+        self.last_printed = line_number;
+    }
+
+    fn emit_context(&mut self, line_number: u64, line: &[u8]) {
+        self.maybe_separator(line_number);
+        self.printer.context(self.path, Some(line_number), line);
+        self.last_printed = line_number;
+    }
+
+    /// Two groups of context/match lines are separated by a `--` line
+    /// unless they're contiguous, mirroring GNU grep.
+    fn maybe_separator(&mut self, line_number: u64) {
+        if self.last_printed != 0 && line_number != self.last_printed + 1 {
+            self.printer.separator();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use grep::GrepBuilder;
+
+    use printer::Printer;
+    use super::{InputBuffer, Searcher};
+
+    fn run(haystack: &str, before: usize, after: usize) -> String {
+        let grep = GrepBuilder::new("bar").build().unwrap();
+        let mut inp = InputBuffer::new();
+        let mut printer = Printer::new(vec![]);
+        {
+            let searcher = Searcher {
+                grep: &grep,
+                path: "test".as_ref(),
+                haystack: Cursor::new(haystack.as_bytes()),
+                inp: &mut inp,
+                printer: &mut printer,
+                before_context: before,
+                after_context: after,
+            };
+            searcher.run().unwrap();
+        }
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn overlapping_windows_dont_duplicate_shared_context_line() {
+        let haystack = "noise\nbar A\nbetween\nbar B\nnoise2\n";
+        let out = run(haystack, 1, 1);
+        assert_eq!(out.matches("between").count(), 1);
+        assert!(!out.contains("--\n"));
+    }
+
+    #[test]
+    fn non_adjacent_match_groups_get_a_separator() {
+        let haystack = "bar A\nnoise\nnoise\nbar B\n";
+        let out = run(haystack, 0, 0);
+        assert_eq!(out.matches("--\n").count(), 1);
+    }
+
+    #[test]
+    fn empty_haystack_produces_no_output() {
+        let out = run("", 0, 0);
+        assert!(out.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod mmap_tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process;
+
+    use super::{MMAP_THRESHOLD, try_mmap};
+
+    fn write_file(name: &str, len: u64) -> fs::File {
+        let path = env::temp_dir()
+            .join(format!("xrep-test-mmap-{}-{}", process::id(), name));
+        let mut file = File::create(&path).unwrap();
+        if len > 0 {
+            let buf = vec![b'x'; len as usize];
+            file.write_all(&buf).unwrap();
+        }
+        file.flush().unwrap();
+        File::open(&path).unwrap()
+    }
+
+    #[test]
+    fn empty_file_does_not_mmap() {
+        let file = write_file("empty", 0);
+        assert!(try_mmap(&file).is_none());
+    }
+
+    #[test]
+    fn file_just_under_threshold_does_not_mmap() {
+        let file = write_file("under", MMAP_THRESHOLD - 1);
+        assert!(try_mmap(&file).is_none());
+    }
+
+    #[test]
+    fn file_at_threshold_does_mmap() {
+        let file = write_file("at", MMAP_THRESHOLD);
+        assert!(try_mmap(&file).is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_regular_file_does_not_mmap() {
+        let file = File::open("/dev/null").unwrap();
+        assert!(try_mmap(&file).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use glob::Pattern;
+use Result;
+
+/// The built-in name -> globs table. Not exhaustive, just enough of
+/// the common cases to be useful; `--type-add` fills in the rest.
+fn builtin_types() -> Vec<(&'static str, &'static [&'static str])> {
+    vec![
+        ("rust", &["*.rs"]),
+        ("py", &["*.py", "*.pyw"]),
+        ("c", &["*.c", "*.h"]),
+        ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh"]),
+        ("go", &["*.go"]),
+        ("js", &["*.js", "*.jsx"]),
+        ("java", &["*.java"]),
+        ("md", &["*.md", "*.markdown"]),
+        ("txt", &["*.txt"]),
+    ]
+}
+
+/// A registry of named file types, each backed by one or more globs.
+/// Backs `-t`/`-T` type filtering and `--type-list`.
+pub struct Types {
+    defs: HashMap<String, Vec<String>>,
+}
+
+impl Types {
+    /// Build the registry from the built-in types plus any
+    /// `name:glob` pairs from `--type-add`, which merge into (rather
+    /// than replace) the built-in table.
+    pub fn new(extra: &[String]) -> Result<Types> {
+        let mut defs = HashMap::new();
+        for (name, globs) in builtin_types() {
+            defs.insert(
+                name.to_string(),
+                globs.iter().map(|g| g.to_string()).collect());
+        }
+        for def in extra {
+            let idx = match def.find(':') {
+                Some(idx) => idx,
+                None => {
+                    errored!(
+                        "invalid --type-add value (expected 'name:glob'): {}",
+                        def);
+                }
+            };
+            let name = &def[..idx];
+            let glob = &def[idx + 1..];
+            defs.entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(glob.to_string());
+        }
+        Ok(Types { defs: defs })
+    }
+
+    /// Compile a filter from the type names given to `-t`/`-T`.
+    pub fn filter(
+        &self,
+        selected: &[String],
+        negated: &[String],
+    ) -> Result<TypeFilter> {
+        Ok(TypeFilter {
+            select: try!(self.compile(selected)),
+            negate: try!(self.compile(negated)),
+        })
+    }
+
+    fn compile(&self, names: &[String]) -> Result<Vec<Pattern>> {
+        let mut pats = vec![];
+        for name in names {
+            let globs = match self.defs.get(name) {
+                Some(globs) => globs,
+                None => errored!("unrecognized file type: {}", name),
+            };
+            for glob in globs {
+                pats.push(try!(Pattern::new(glob)));
+            }
+        }
+        Ok(pats)
+    }
+
+    /// Print every known type and its globs, one per line.
+    pub fn print_list(&self) {
+        let mut names: Vec<&String> = self.defs.keys().collect();
+        names.sort();
+        for name in names {
+            println!("{}: {}", name, self.defs[name].join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Types;
+
+    #[test]
+    fn type_add_merges_with_builtins() {
+        let extra = vec!["foo:*.foo".to_string()];
+        let types = Types::new(&extra).unwrap();
+        assert!(types.defs.contains_key("foo"));
+        assert_eq!(types.defs["foo"], vec!["*.foo".to_string()]);
+        // Built-ins are still present alongside the new type, since
+        // --type-list --type-add should show both.
+        assert!(types.defs.contains_key("rust"));
+    }
+
+    #[test]
+    fn type_add_appends_to_existing_builtin() {
+        let extra = vec!["rust:*.rs.in".to_string()];
+        let types = Types::new(&extra).unwrap();
+        assert!(types.defs["rust"].contains(&"*.rs".to_string()));
+        assert!(types.defs["rust"].contains(&"*.rs.in".to_string()));
+    }
+}
+
+/// A compiled `-t`/`-T` filter, ready to test paths against.
+pub struct TypeFilter {
+    select: Vec<Pattern>,
+    negate: Vec<Pattern>,
+}
+
+impl TypeFilter {
+    /// Returns true if a file matching `text` should be skipped: it
+    /// fails to match any `-t` type (when at least one was given), or
+    /// it matches a `-T` type.
+    pub fn excluded(&self, text: &str) -> bool {
+        if !self.select.is_empty() && !self.select.iter().any(|p| p.is_match(text)) {
+            return true;
+        }
+        self.negate.iter().any(|p| p.is_match(text))
+    }
+}
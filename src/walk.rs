@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use walkdir::{self, WalkDir};
+
+use ignore::Ignore;
+
+/// A serial, single-threaded iterator over the files beneath a path,
+/// honoring the given ignore rules plus the `.gitignore` of each
+/// directory it descends into. Like the search walk, the root path
+/// itself is never filtered. Used by `--files`, where there's no
+/// searching to parallelize and a plain walk is simplest.
+pub struct Iter {
+    ig: Ignore,
+    // `stack[d]` holds the rules for the children of the directory open
+    // at depth `d`, with that directory's `.gitignore` layered on top.
+    stack: Vec<Ignore>,
+    it: walkdir::IntoIter,
+}
+
+impl Iter {
+    pub fn new(ig: Ignore, wd: WalkDir) -> Iter {
+        Iter { ig: ig, stack: vec![], it: wd.into_iter() }
+    }
+}
+
+impl Iterator for Iter {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            let dent = match self.it.next() {
+                None => return None,
+                Some(Err(_)) => continue,
+                Some(Ok(dent)) => dent,
+            };
+            let path = dent.path().to_path_buf();
+            let is_dir = dent.file_type().is_dir();
+            let depth = dent.depth();
+            self.stack.truncate(depth);
+            let ig = match self.stack.last() {
+                Some(ig) => ig.clone(),
+                None => self.ig.clone(),
+            };
+            if depth > 0 && ig.ignored(&path, is_dir) {
+                if is_dir {
+                    self.it.skip_current_dir();
+                }
+                continue;
+            }
+            if is_dir {
+                self.stack.push(ig.push(&path));
+                continue;
+            }
+            return Some(path);
+        }
+    }
+}